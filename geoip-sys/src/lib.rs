@@ -37,6 +37,21 @@ extern {
     pub fn GeoIP_set_charset(db: RawGeoIp, charset: c_int) -> c_int;
     pub fn GeoIP_region_name_by_code(country_code: *const c_char, region_code: *const c_char) -> *const c_char;
     pub fn GeoIP_time_zone_by_country_and_region(country_code: *const c_char, region_code: *const c_char) -> *const c_char;
+    pub fn GeoIP_id_by_ipnum_gl(db: RawGeoIp, ipnum: c_ulong, gl: *mut GeoIpLookup) -> c_int;
+    pub fn GeoIP_id_by_ipnum_v6_gl(db: RawGeoIp, ipnum: In6Addr, gl: *mut GeoIpLookup) -> c_int;
+    // Returns a 2-element `char *[]`: the first and last address of the
+    // block matching `addr`, as decimal-dotted strings. Must be freed with
+    // `GeoIP_range_by_ip_delete`.
+    pub fn GeoIP_range_by_ip(db: RawGeoIp, addr: *const c_char) -> *mut *mut c_char;
+    pub fn GeoIP_range_by_ip_delete(range: *mut *mut c_char);
+
+    // Sized to the current libGeoIP release; callers must still treat any
+    // id as untrusted and bounds-check rather than index directly, since
+    // older/newer libGeoIP builds have shipped different table lengths.
+    pub static GeoIP_country_code: [[c_char; 3]; 256];
+    pub static GeoIP_country_code3: [[c_char; 4]; 256];
+    pub static GeoIP_country_name: [*const c_char; 256];
+    pub static GeoIP_country_continent: [[c_char; 3]; 256];
 }
 
 #[repr(C)]
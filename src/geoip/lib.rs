@@ -7,11 +7,16 @@ use geoip_sys;
 extern crate lazy_static;
 use libc;
 
+pub mod mmdb;
+pub mod multi;
+#[cfg(feature = "updater")]
+pub mod updater;
+
 use libc::{c_char, c_int, c_ulong, c_void};
 use std::error::Error;
 use std::ffi;
 use std::fmt::{self, Debug};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::str::Utf8Error;
@@ -35,7 +40,7 @@ pub enum Options {
     MmapCache = 8,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DBType {
     CountryEdition = 1,
     RegionEditionRev0 = 7,
@@ -84,6 +89,26 @@ pub struct ASInfo {
     pub netmask: u32,
 }
 
+/// A single descriptive string returned by the org/ISP/domain/netspeed
+/// databases, which (unlike `ASInfo`) don't encode any further structure
+/// for this crate to parse out.
+#[derive(Debug, Clone, RustcDecodable, RustcEncodable)]
+pub struct NameInfo {
+    pub name: String,
+    pub netmask: u32,
+}
+
+/// The country-only subset of `CityInfo`, for callers of a country-edition
+/// database who don't need the weight of a full city lookup.
+#[derive(Debug, Clone, RustcDecodable, RustcEncodable)]
+pub struct CountryInfo {
+    pub code: Option<String>,
+    pub code3: Option<String>,
+    pub name: Option<String>,
+    pub continent: Option<String>,
+    pub netmask: u32,
+}
+
 #[derive(Debug, Clone, RustcDecodable, RustcEncodable)]
 pub struct CityInfo {
     pub country_code: Option<String>,
@@ -116,6 +141,19 @@ fn maybe_code(code: u32) -> Option<u32> {
     }
 }
 
+fn fixed_str(chars: &[c_char]) -> Option<String> {
+    let bytes: Vec<u8> = chars
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    if bytes.is_empty() {
+        None
+    } else {
+        String::from_utf8(bytes).ok()
+    }
+}
+
 impl CityInfo {
     unsafe fn from_geoiprecord(res: &geoip_sys::GeoIpRecord) -> CityInfo {
         CityInfo {
@@ -293,7 +331,7 @@ impl GeoIp {
         let db = unsafe {
             // GeoIP_open_type initialises global state causing races
             let _lock = LOCK.lock().unwrap();
-            geoip_sys::GeoIP_open_type(db_type.clone() as c_int, options as c_int)
+            geoip_sys::GeoIP_open_type(db_type as c_int, options as c_int)
         };
         if db.is_null() {
             return Err(OpenTypeError::OpenFailed(db_type));
@@ -402,7 +440,7 @@ impl GeoIp {
         }
     }
 
-    pub fn as_info_by_ip(&self, ip: IpAddr) -> Option<ASInfo> {
+    fn name_by_ip(&self, ip: IpAddr) -> Option<NameInfo> {
         let mut gl = geoip_sys::GeoIpLookup::new();
         let cres = match CNetworkIp::new(ip) {
             CNetworkIp::V4(ip) => unsafe {
@@ -416,10 +454,18 @@ impl GeoIp {
         if cres.is_null() {
             return None;
         }
-        let description = match maybe_string(cres) {
+        let name = match maybe_string(cres) {
             None => return None,
-            Some(description) => description,
+            Some(name) => name,
         };
+        Some(NameInfo {
+            name,
+            netmask: gl.netmask as u32,
+        })
+    }
+
+    pub fn as_info_by_ip(&self, ip: IpAddr) -> Option<ASInfo> {
+        let NameInfo { name: description, netmask } = self.name_by_ip(ip)?;
         let mut di = description.splitn(2, ' ');
         let asn = match di.next() {
             None => return None,
@@ -440,10 +486,115 @@ impl GeoIp {
         let as_info = ASInfo {
             asn,
             name: name.to_string(),
-            netmask: gl.netmask as u32,
+            netmask,
         };
         Some(as_info)
     }
+
+    /// Query an `ORGEdition`/`ORGEditionV6` database for the organization
+    /// name owning `ip`.
+    pub fn org_by_ip(&self, ip: IpAddr) -> Option<NameInfo> {
+        self.name_by_ip(ip)
+    }
+
+    /// Query an `ISPEdition`/`ISPEditionV6` database for the ISP name
+    /// serving `ip`.
+    pub fn isp_by_ip(&self, ip: IpAddr) -> Option<NameInfo> {
+        self.name_by_ip(ip)
+    }
+
+    /// Query a `DomainEdition`/`DomainEditionV6` database for the domain
+    /// name associated with `ip`.
+    pub fn domain_by_ip(&self, ip: IpAddr) -> Option<NameInfo> {
+        self.name_by_ip(ip)
+    }
+
+    /// Query a `NetSpeedEdition`/`NetSpeedEditionRev1`/`*V6` database for
+    /// the connection type serving `ip` (e.g. `"Cable/DSL"`, `"Dialup"`).
+    pub fn netspeed_by_ip(&self, ip: IpAddr) -> Option<NameInfo> {
+        self.name_by_ip(ip)
+    }
+
+    /// Look up just the country for `ip` against a country-edition
+    /// database, without the cost of loading and parsing a full city
+    /// record.
+    pub fn country_info_by_ip(&self, ip: IpAddr) -> Option<CountryInfo> {
+        let mut gl = geoip_sys::GeoIpLookup::new();
+        let id = match CNetworkIp::new(ip) {
+            CNetworkIp::V4(ip) => unsafe {
+                geoip_sys::GeoIP_id_by_ipnum_gl(self.db, ip, &mut gl)
+            },
+            CNetworkIp::V6(ip) => unsafe {
+                geoip_sys::GeoIP_id_by_ipnum_v6_gl(self.db, ip, &mut gl)
+            },
+        };
+        if id <= 0 {
+            return None;
+        }
+        let id = id as usize;
+        // `id` comes from libGeoIP at runtime and isn't guaranteed to fit
+        // the country table lengths declared on the Rust side, so look up
+        // with `get` rather than indexing directly.
+        unsafe {
+            let code = geoip_sys::GeoIP_country_code.get(id)?;
+            let code3 = geoip_sys::GeoIP_country_code3.get(id)?;
+            let name = geoip_sys::GeoIP_country_name.get(id)?;
+            let continent = geoip_sys::GeoIP_country_continent.get(id)?;
+            Some(CountryInfo {
+                code: fixed_str(code),
+                code3: fixed_str(code3),
+                name: maybe_string(*name),
+                continent: fixed_str(continent),
+                netmask: gl.netmask as u32,
+            })
+        }
+    }
+
+    /// The first and last address of the network block that `ip`'s
+    /// lookup applies to.
+    pub fn network_range_by_ip(&self, ip: IpAddr) -> Option<(IpAddr, IpAddr)> {
+        // GeoIP_range_by_ip has no v6 counterpart in libGeoIP.
+        if !ip.is_ipv4() {
+            return None;
+        }
+        let addr = ffi::CString::new(ip.to_string()).ok()?;
+        let range = unsafe { geoip_sys::GeoIP_range_by_ip(self.db, addr.as_ptr()) };
+        if range.is_null() {
+            return None;
+        }
+        let result = unsafe {
+            let left = maybe_string(*range);
+            let right = maybe_string(*range.add(1));
+            geoip_sys::GeoIP_range_by_ip_delete(range);
+            (left, right)
+        };
+        match result {
+            (Some(left), Some(right)) => {
+                let left: Ipv4Addr = left.parse().ok()?;
+                let right: Ipv4Addr = right.parse().ok()?;
+                Some((IpAddr::V4(left), IpAddr::V4(right)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Combine a lookup's `netmask` with the IP address that was queried to
+/// produce the CIDR block it applies to, e.g. for indexing results by
+/// network prefix instead of by individual address.
+pub fn cidr_for(ip: IpAddr, netmask: u32) -> (IpAddr, u8) {
+    match ip {
+        IpAddr::V4(addr) => {
+            let prefix = netmask.min(32);
+            let mask: u32 = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+            (IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask)), prefix as u8)
+        }
+        IpAddr::V6(addr) => {
+            let prefix = netmask.min(128);
+            let mask: u128 = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+            (IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask)), prefix as u8)
+        }
+    }
 }
 
 impl Drop for GeoIp {
@@ -0,0 +1,571 @@
+//! Pure-Rust reader for the MaxMind DB (`.mmdb`) format used by the
+//! GeoIP2 / GeoLite2 database editions.
+//!
+//! This is a from-scratch parser of the binary format described at
+//! <https://maxmind.github.io/MaxMind-DB/>: a binary search tree, a
+//! 16-byte separator, a type-tagged data section, and a metadata map
+//! located by scanning backwards from the end of the file for the
+//! marker `\xab\xcd\xefMaxMind.com`. It exists alongside the libGeoIP
+//! binding in the rest of this crate so callers can move to the current
+//! MaxMind format without rewriting their lookup code: the lookup
+//! methods below return the same `CityInfo`/`ASInfo` structs as `GeoIp`.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use crate::{ASInfo, CityInfo};
+
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+const DATA_SECTION_SEPARATOR: u32 = 16;
+
+#[derive(Debug, Clone)]
+pub enum MmdbError {
+    Io(PathBuf, String),
+    MetadataNotFound(PathBuf),
+    InvalidMetadata(&'static str),
+    InvalidData(&'static str),
+    UnsupportedIpVersion(IpAddr),
+}
+
+impl fmt::Display for MmdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MmdbError::Io(ref path, ref err) => {
+                write!(f, "Failed to read mmdb file '{}': {}", path.display(), err)
+            }
+            MmdbError::MetadataNotFound(ref path) => write!(
+                f,
+                "No mmdb metadata marker found in '{}'",
+                path.display()
+            ),
+            MmdbError::InvalidMetadata(why) => write!(f, "Invalid mmdb metadata: {}", why),
+            MmdbError::InvalidData(why) => write!(f, "Invalid mmdb data section: {}", why),
+            MmdbError::UnsupportedIpVersion(ip) => {
+                write!(f, "Database cannot resolve IP address {}", ip)
+            }
+        }
+    }
+}
+
+impl Error for MmdbError {
+    fn description(&self) -> &str {
+        match *self {
+            MmdbError::Io(..) => "failed to read mmdb file",
+            MmdbError::MetadataNotFound(_) => "no mmdb metadata marker found",
+            MmdbError::InvalidMetadata(_) => "invalid mmdb metadata",
+            MmdbError::InvalidData(_) => "invalid mmdb data section",
+            MmdbError::UnsupportedIpVersion(_) => "database cannot resolve this IP version",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    String(String),
+    Double(f64),
+    Bytes(Vec<u8>),
+    Uint16(u16),
+    Uint32(u32),
+    Map(BTreeMap<String, Value>),
+    Int32(i32),
+    Uint64(u64),
+    Uint128(u128),
+    Array(Vec<Value>),
+    Boolean(bool),
+    Float(f32),
+}
+
+impl Value {
+    fn as_map(&self) -> Option<&BTreeMap<String, Value>> {
+        match *self {
+            Value::Map(ref m) => Some(m),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Double(v) => Some(v),
+            Value::Float(v) => Some(v as f64),
+            _ => None,
+        }
+    }
+
+    fn get<'a>(&'a self, key: &str) -> Option<&'a Value> {
+        self.as_map().and_then(|m| m.get(key))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Metadata {
+    node_count: u32,
+    record_size: u16,
+    ip_version: u16,
+}
+
+impl Metadata {
+    fn from_value(value: &Value) -> Result<Metadata, MmdbError> {
+        let node_count = match value.get("node_count") {
+            Some(&Value::Uint32(n)) => n,
+            Some(&Value::Uint16(n)) => n as u32,
+            _ => return Err(MmdbError::InvalidMetadata("missing node_count")),
+        };
+        let record_size = match value.get("record_size") {
+            Some(&Value::Uint16(n)) => n,
+            Some(&Value::Uint32(n)) => n as u16,
+            _ => return Err(MmdbError::InvalidMetadata("missing record_size")),
+        };
+        let ip_version = match value.get("ip_version") {
+            Some(&Value::Uint16(n)) => n,
+            Some(&Value::Uint32(n)) => n as u16,
+            _ => return Err(MmdbError::InvalidMetadata("missing ip_version")),
+        };
+        if record_size != 24 && record_size != 28 && record_size != 32 {
+            return Err(MmdbError::InvalidMetadata("unsupported record_size"));
+        }
+        Ok(Metadata {
+            node_count,
+            record_size,
+            ip_version,
+        })
+    }
+}
+
+/// Decode the type-tagged value starting at `offset`, returning it along
+/// with the offset of whatever follows it in the stream (pointer targets
+/// are followed for the value but don't move this cursor). `data_section_start`
+/// is the base that pointer payloads found along the way are relative to.
+fn decode_value(
+    data: &[u8],
+    offset: usize,
+    data_section_start: usize,
+) -> Result<(Value, usize), MmdbError> {
+    if offset >= data.len() {
+        return Err(MmdbError::InvalidData("offset past end of data section"));
+    }
+    let ctrl = data[offset];
+    let mut type_num = ctrl >> 5;
+    let mut cursor = offset + 1;
+    if type_num == 0 {
+        let extra = *data
+            .get(cursor)
+            .ok_or(MmdbError::InvalidData("truncated extended type"))?;
+        type_num = 7 + extra;
+        cursor += 1;
+    }
+
+    if type_num == 1 {
+        return decode_pointer(data, ctrl, cursor, data_section_start);
+    }
+
+    let size_bits = ctrl & 0x1f;
+    let (size, cursor) = match size_bits {
+        0..=28 => (size_bits as usize, cursor),
+        29 => {
+            let b = *data.get(cursor).ok_or(MmdbError::InvalidData("truncated size"))?;
+            (29 + b as usize, cursor + 1)
+        }
+        30 => {
+            let bytes = data
+                .get(cursor..cursor + 2)
+                .ok_or(MmdbError::InvalidData("truncated size"))?;
+            (285 + u16::from_be_bytes([bytes[0], bytes[1]]) as usize, cursor + 2)
+        }
+        31 => {
+            let bytes = data
+                .get(cursor..cursor + 3)
+                .ok_or(MmdbError::InvalidData("truncated size"))?;
+            (
+                65821 + u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize,
+                cursor + 3,
+            )
+        }
+        _ => unreachable!(),
+    };
+
+    match type_num {
+        2 => {
+            let bytes = data
+                .get(cursor..cursor + size)
+                .ok_or(MmdbError::InvalidData("truncated string"))?;
+            let s = std::str::from_utf8(bytes)
+                .map_err(|_| MmdbError::InvalidData("string is not valid utf-8"))?;
+            Ok((Value::String(s.to_string()), cursor + size))
+        }
+        3 => {
+            let bytes = data
+                .get(cursor..cursor + 8)
+                .ok_or(MmdbError::InvalidData("truncated double"))?;
+            Ok((
+                Value::Double(f64::from_be_bytes(bytes.try_into().unwrap())),
+                cursor + 8,
+            ))
+        }
+        4 => {
+            let bytes = data
+                .get(cursor..cursor + size)
+                .ok_or(MmdbError::InvalidData("truncated bytes"))?;
+            Ok((Value::Bytes(bytes.to_vec()), cursor + size))
+        }
+        5 => {
+            let (v, next) = decode_uint(data, cursor, size)?;
+            Ok((Value::Uint16(v as u16), next))
+        }
+        6 => {
+            let (v, next) = decode_uint(data, cursor, size)?;
+            Ok((Value::Uint32(v as u32), next))
+        }
+        7 => {
+            let mut map = BTreeMap::new();
+            let mut cursor = cursor;
+            for _ in 0..size {
+                let (key, next) = decode_value(data, cursor, data_section_start)?;
+                let key = key
+                    .as_str()
+                    .ok_or(MmdbError::InvalidData("map key is not a string"))?
+                    .to_string();
+                let (value, next) = decode_value(data, next, data_section_start)?;
+                map.insert(key, value);
+                cursor = next;
+            }
+            Ok((Value::Map(map), cursor))
+        }
+        8 => {
+            let (v, next) = decode_uint(data, cursor, size)?;
+            Ok((Value::Int32(v as i32), next))
+        }
+        9 => {
+            let (v, next) = decode_uint(data, cursor, size)?;
+            Ok((Value::Uint64(v as u64), next))
+        }
+        10 => {
+            let (v, next) = decode_uint128(data, cursor, size)?;
+            Ok((Value::Uint128(v), next))
+        }
+        11 => {
+            let mut items = Vec::with_capacity(size);
+            let mut cursor = cursor;
+            for _ in 0..size {
+                let (item, next) = decode_value(data, cursor, data_section_start)?;
+                items.push(item);
+                cursor = next;
+            }
+            Ok((Value::Array(items), cursor))
+        }
+        14 => Ok((Value::Boolean(size_bits != 0), cursor)),
+        15 => {
+            let bytes = data
+                .get(cursor..cursor + 4)
+                .ok_or(MmdbError::InvalidData("truncated float"))?;
+            Ok((
+                Value::Float(f32::from_be_bytes(bytes.try_into().unwrap())),
+                cursor + 4,
+            ))
+        }
+        _ => Err(MmdbError::InvalidData("unknown data section type")),
+    }
+}
+
+fn decode_uint(data: &[u8], offset: usize, size: usize) -> Result<(u128, usize), MmdbError> {
+    let (v, next) = decode_uint128(data, offset, size)?;
+    Ok((v, next))
+}
+
+fn decode_uint128(data: &[u8], offset: usize, size: usize) -> Result<(u128, usize), MmdbError> {
+    let bytes = data
+        .get(offset..offset + size)
+        .ok_or(MmdbError::InvalidData("truncated integer"))?;
+    let mut v: u128 = 0;
+    for &b in bytes {
+        v = (v << 8) | b as u128;
+    }
+    Ok((v, offset + size))
+}
+
+fn decode_pointer(
+    data: &[u8],
+    ctrl: u8,
+    cursor: usize,
+    data_section_start: usize,
+) -> Result<(Value, usize), MmdbError> {
+    let size_class = (ctrl >> 3) & 0x3;
+    let top = (ctrl & 0x7) as u32;
+    let (pointer, cursor) = match size_class {
+        0 => {
+            let b = *data.get(cursor).ok_or(MmdbError::InvalidData("truncated pointer"))?;
+            ((top << 8) | b as u32, cursor + 1)
+        }
+        1 => {
+            let bytes = data
+                .get(cursor..cursor + 2)
+                .ok_or(MmdbError::InvalidData("truncated pointer"))?;
+            (
+                ((top << 16) | (bytes[0] as u32) << 8 | bytes[1] as u32) + 2048,
+                cursor + 2,
+            )
+        }
+        2 => {
+            let bytes = data
+                .get(cursor..cursor + 3)
+                .ok_or(MmdbError::InvalidData("truncated pointer"))?;
+            (
+                ((top << 24) | (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32)
+                    + 526336,
+                cursor + 3,
+            )
+        }
+        3 => {
+            let bytes = data
+                .get(cursor..cursor + 4)
+                .ok_or(MmdbError::InvalidData("truncated pointer"))?;
+            (u32::from_be_bytes(bytes.try_into().unwrap()), cursor + 4)
+        }
+        _ => unreachable!(),
+    };
+    // Pointer payloads are offsets relative to the start of the section
+    // they were read from (the main data section for search-tree records,
+    // the metadata section for metadata fields), not the start of the file.
+    let (value, _) = decode_value(data, data_section_start + pointer as usize, data_section_start)?;
+    Ok((value, cursor))
+}
+
+/// A single opened `.mmdb` file.
+pub struct Reader {
+    data: Vec<u8>,
+    metadata: Metadata,
+}
+
+impl Reader {
+    pub fn open(path: &Path) -> Result<Reader, MmdbError> {
+        let data =
+            fs::read(path).map_err(|err| MmdbError::Io(path.to_owned(), err.to_string()))?;
+
+        let marker_at = data
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or_else(|| MmdbError::MetadataNotFound(path.to_owned()))?;
+        let metadata_start = marker_at + METADATA_MARKER.len();
+
+        // The metadata map is itself encoded in the standard data-section
+        // format; any pointer inside it is relative to the metadata
+        // section's own start, since the real data section's bounds aren't
+        // known until the metadata (node_count, record_size) is decoded.
+        let (metadata_value, _) = decode_value(&data, metadata_start, metadata_start)?;
+        let metadata = Metadata::from_value(&metadata_value)?;
+
+        Ok(Reader { data, metadata })
+    }
+
+    fn node_bytes(&self) -> usize {
+        (self.metadata.record_size as usize) * 2 / 8
+    }
+
+    fn read_node(&self, node_index: u32, which: u8) -> Result<u32, MmdbError> {
+        let node_bytes = self.node_bytes();
+        let base = node_index as usize * node_bytes;
+        let node = self
+            .data
+            .get(base..base + node_bytes)
+            .ok_or(MmdbError::InvalidData("node index out of range"))?;
+        Ok(match self.metadata.record_size {
+            24 => {
+                let o = which as usize * 3;
+                (node[o] as u32) << 16 | (node[o + 1] as u32) << 8 | node[o + 2] as u32
+            }
+            28 => {
+                if which == 0 {
+                    ((node[3] & 0xf0) as u32) << 20
+                        | (node[0] as u32) << 16
+                        | (node[1] as u32) << 8
+                        | node[2] as u32
+                } else {
+                    ((node[3] & 0x0f) as u32) << 24
+                        | (node[4] as u32) << 16
+                        | (node[5] as u32) << 8
+                        | node[6] as u32
+                }
+            }
+            32 => {
+                let o = which as usize * 4;
+                (node[o] as u32) << 24
+                    | (node[o + 1] as u32) << 16
+                    | (node[o + 2] as u32) << 8
+                    | node[o + 3] as u32
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    /// The 16 (or, for a v4-only tree, 4) address bytes to walk the search
+    /// tree with, and the bit at which those bytes start being meaningful
+    /// (the prefix length MaxMind reports for a match is relative to this,
+    /// not to the full walk).
+    ///
+    /// IPv4 networks in a mixed v4/v6 tree are stored natively under
+    /// `::/96` (the all-zero prefix), not under the `::ffff:0:0/96` alias,
+    /// so a v4 lookup walks 96 zero bits down to the IPv4 subtree root and
+    /// then the 32 bits of the address itself.
+    fn address_bytes(&self, ip: IpAddr) -> Result<(Vec<u8>, u32), MmdbError> {
+        match ip {
+            IpAddr::V4(addr) if self.metadata.ip_version == 4 => Ok((addr.octets().to_vec(), 0)),
+            IpAddr::V4(addr) if self.metadata.ip_version == 6 => {
+                let mut bytes = vec![0u8; 12];
+                bytes.extend_from_slice(&addr.octets());
+                Ok((bytes, 96))
+            }
+            IpAddr::V6(addr) if self.metadata.ip_version == 6 => Ok((addr.octets().to_vec(), 0)),
+            _ => Err(MmdbError::UnsupportedIpVersion(ip)),
+        }
+    }
+
+    /// Walk the search tree for `ip`, returning the offset of its record in
+    /// the data section together with the matching network's prefix length
+    /// (0-32 for an IPv4 match, 0-128 for an IPv6 one).
+    fn lookup(&self, ip: IpAddr) -> Result<Option<(usize, u32)>, MmdbError> {
+        let (address, start_bit) = self.address_bytes(ip)?;
+        let bit_count = address.len() * 8;
+        let mut node = 0u32;
+        for bit in 0..bit_count {
+            let byte = address[bit / 8];
+            let which = (byte >> (7 - (bit % 8))) & 1;
+            let record = self.read_node(node, which)?;
+            if record == self.metadata.node_count {
+                return Ok(None);
+            }
+            if record > self.metadata.node_count {
+                let offset = record - self.metadata.node_count - DATA_SECTION_SEPARATOR;
+                let prefix = (bit + 1).saturating_sub(start_bit as usize) as u32;
+                return Ok(Some((offset as usize, prefix)));
+            }
+            node = record;
+        }
+        Ok(None)
+    }
+
+    fn record(&self, ip: IpAddr) -> Result<Option<(Value, u32)>, MmdbError> {
+        let search_tree_size = self.node_bytes() * self.metadata.node_count as usize;
+        let data_section_start = search_tree_size + DATA_SECTION_SEPARATOR as usize;
+        match self.lookup(ip)? {
+            None => Ok(None),
+            Some((offset, netmask)) => {
+                let (value, _) = decode_value(
+                    &self.data,
+                    data_section_start + offset,
+                    data_section_start,
+                )?;
+                Ok(Some((value, netmask)))
+            }
+        }
+    }
+
+    pub fn city_info_by_ip(&self, ip: IpAddr) -> Result<Option<CityInfo>, MmdbError> {
+        let (record, netmask) = match self.record(ip)? {
+            None => return Ok(None),
+            Some(found) => found,
+        };
+        let name = |node: Option<&Value>| {
+            node.and_then(|v| v.get("names"))
+                .and_then(|n| n.get("en"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        Ok(Some(CityInfo {
+            country_code: record
+                .get("country")
+                .and_then(|c| c.get("iso_code"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            country_code3: None,
+            country_name: name(record.get("country")),
+            region: record
+                .get("subdivisions")
+                .and_then(|s| match s {
+                    Value::Array(items) => items.first(),
+                    _ => None,
+                })
+                .and_then(|s| s.get("iso_code"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            city: name(record.get("city")),
+            postal_code: record
+                .get("postal")
+                .and_then(|p| p.get("code"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            latitude: record
+                .get("location")
+                .and_then(|l| l.get("latitude"))
+                .and_then(Value::as_f64)
+                .unwrap_or(0.0) as f32,
+            longitude: record
+                .get("location")
+                .and_then(|l| l.get("longitude"))
+                .and_then(Value::as_f64)
+                .unwrap_or(0.0) as f32,
+            dma_code: None,
+            area_code: None,
+            continent_code: record
+                .get("continent")
+                .and_then(|c| c.get("code"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            netmask,
+        }))
+    }
+
+    pub fn as_info_by_ip(&self, ip: IpAddr) -> Result<Option<ASInfo>, MmdbError> {
+        let (record, netmask) = match self.record(ip)? {
+            None => return Ok(None),
+            Some(found) => found,
+        };
+        let asn = match record.get("autonomous_system_number").and_then(|v| match *v {
+            Value::Uint32(n) => Some(n),
+            Value::Uint16(n) => Some(n as u32),
+            _ => None,
+        }) {
+            Some(asn) => asn,
+            None => return Ok(None),
+        };
+        let name = record
+            .get("autonomous_system_organization")
+            .and_then(Value::as_str)
+            .unwrap_or("(none)")
+            .to_string();
+        Ok(Some(ASInfo { asn, name, netmask }))
+    }
+}
+
+impl fmt::Debug for Reader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("mmdb::Reader")
+            .field("record_size", &self.metadata.record_size)
+            .field("node_count", &self.metadata.node_count)
+            .field("ip_version", &self.metadata.ip_version)
+            .finish()
+    }
+}
+
+#[test]
+fn mmdb_test_city() {
+    let reader = Reader::open(Path::new("/opt/geoip/GeoLite2-City.mmdb")).unwrap();
+    let ip = IpAddr::V4("8.8.8.8".parse().unwrap());
+    let res = reader.city_info_by_ip(ip).unwrap().unwrap();
+    assert_eq!(res.country_code, Some("US".to_string()));
+}
+
+#[test]
+fn mmdb_test_open_fail() {
+    let err = Reader::open(Path::new("foobar.mmdb")).unwrap_err();
+    assert!(format!("{}", err).contains("foobar.mmdb"));
+}
@@ -0,0 +1,157 @@
+//! Merging lookups across several opened databases.
+//!
+//! Deployments that open a City edition alongside an ASNum or Org edition
+//! and stitch the per-IP answers together by hand can use a `GeoIpSet`
+//! instead: it holds every opened edition keyed by `DBType` and answers a
+//! single `resolve` call with whatever fields the loaded databases cover.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::{DBType, GeoIp, Options};
+
+/// The union of whatever per-edition fields a `GeoIpSet` could find for an
+/// IP, across every database it has loaded. Fields stay `None` when no
+/// loaded database answers them.
+#[derive(Debug, Clone, Default)]
+pub struct CombinedInfo {
+    pub country_code: Option<String>,
+    pub country_code3: Option<String>,
+    pub country_name: Option<String>,
+    pub region: Option<String>,
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+    pub latitude: Option<f32>,
+    pub longitude: Option<f32>,
+    pub continent_code: Option<String>,
+    pub asn: Option<u32>,
+    pub as_name: Option<String>,
+    pub org: Option<String>,
+    pub isp: Option<String>,
+    pub domain: Option<String>,
+    pub netspeed: Option<String>,
+}
+
+const CITY_EDITIONS: &[DBType] = &[
+    DBType::CityEditionRev1,
+    DBType::CityEditionRev1V6,
+    DBType::CityEditionRev0,
+    DBType::CityEditionRev0V6,
+];
+const ASNUM_EDITIONS: &[DBType] = &[DBType::ASNUMEdition, DBType::ASNumEditionV6];
+const ORG_EDITIONS: &[DBType] = &[DBType::ORGEdition, DBType::ORGEditionV6];
+const ISP_EDITIONS: &[DBType] = &[DBType::ISPEdition, DBType::ISPEditionV6];
+const DOMAIN_EDITIONS: &[DBType] = &[DBType::DomainEdition, DBType::DomainEditionV6];
+const NETSPEED_EDITIONS: &[DBType] = &[
+    DBType::NetSpeedEdition,
+    DBType::NetSpeedEditionRev1,
+    DBType::NetSpeedEditionRev1V6,
+];
+
+/// Builds a [`GeoIpSet`], opening each requested edition and silently
+/// dropping any that fail to open so that one unavailable database
+/// doesn't prevent the rest of the set from being usable.
+#[derive(Default)]
+pub struct GeoIpSetBuilder {
+    databases: HashMap<DBType, GeoIp>,
+}
+
+impl GeoIpSetBuilder {
+    pub fn new() -> GeoIpSetBuilder {
+        GeoIpSetBuilder {
+            databases: HashMap::new(),
+        }
+    }
+
+    /// Open `db_type` from `path`, adding it to the set on success.
+    pub fn with_path(mut self, db_type: DBType, path: &Path, options: Options) -> Self {
+        if let Ok(db) = GeoIp::open(path, options) {
+            self.databases.insert(db_type, db);
+        }
+        self
+    }
+
+    /// Open `db_type` from its default libGeoIP location, adding it to the
+    /// set on success.
+    pub fn with_type(mut self, db_type: DBType, options: Options) -> Self {
+        if let Ok(db) = GeoIp::open_type(db_type, options) {
+            self.databases.insert(db_type, db);
+        }
+        self
+    }
+
+    pub fn build(self) -> GeoIpSet {
+        GeoIpSet {
+            databases: self.databases,
+        }
+    }
+}
+
+/// A collection of opened `GeoIp` databases, resolved together per IP.
+pub struct GeoIpSet {
+    databases: HashMap<DBType, GeoIp>,
+}
+
+impl GeoIpSet {
+    pub fn builder() -> GeoIpSetBuilder {
+        GeoIpSetBuilder::new()
+    }
+
+    fn first_loaded(&self, editions: &[DBType]) -> Option<&GeoIp> {
+        editions.iter().find_map(|db_type| self.databases.get(db_type))
+    }
+
+    /// Look up `ip` against every loaded database, merging whatever each
+    /// one can answer into a single `CombinedInfo`.
+    pub fn resolve(&self, ip: IpAddr) -> CombinedInfo {
+        let mut combined = CombinedInfo::default();
+
+        if let Some(city) = self
+            .first_loaded(CITY_EDITIONS)
+            .and_then(|db| db.city_info_by_ip(ip))
+        {
+            combined.country_code = city.country_code;
+            combined.country_code3 = city.country_code3;
+            combined.country_name = city.country_name;
+            combined.region = city.region;
+            combined.city = city.city;
+            combined.postal_code = city.postal_code;
+            combined.latitude = Some(city.latitude);
+            combined.longitude = Some(city.longitude);
+            combined.continent_code = city.continent_code;
+        }
+
+        if let Some(as_info) = self
+            .first_loaded(ASNUM_EDITIONS)
+            .and_then(|db| db.as_info_by_ip(ip))
+        {
+            combined.asn = Some(as_info.asn);
+            combined.as_name = Some(as_info.name);
+        }
+
+        if let Some(info) = self.first_loaded(ORG_EDITIONS).and_then(|db| db.org_by_ip(ip)) {
+            combined.org = Some(info.name);
+        }
+
+        if let Some(info) = self.first_loaded(ISP_EDITIONS).and_then(|db| db.isp_by_ip(ip)) {
+            combined.isp = Some(info.name);
+        }
+
+        if let Some(info) = self
+            .first_loaded(DOMAIN_EDITIONS)
+            .and_then(|db| db.domain_by_ip(ip))
+        {
+            combined.domain = Some(info.name);
+        }
+
+        if let Some(info) = self
+            .first_loaded(NETSPEED_EDITIONS)
+            .and_then(|db| db.netspeed_by_ip(ip))
+        {
+            combined.netspeed = Some(info.name);
+        }
+
+        combined
+    }
+}
@@ -0,0 +1,150 @@
+//! Downloading and refreshing GeoIP database files.
+//!
+//! Rather than requiring a populated `/opt/geoip`, applications can use
+//! this module to fetch the edition they need into a directory of their
+//! choosing and keep it current, handing the resulting path straight to
+//! [`GeoIp::open`](crate::GeoIp::open).
+//!
+//! MaxMind retired the free `geolite.maxmind.com` download mirror in
+//! 2019; fetching current `.dat` editions
+//! now requires a MaxMind account and license key against one of their
+//! gated endpoints (see MaxMind's GeoIP Update documentation for the URL
+//! and query parameters that applies to your subscription). Rather than
+//! guess at and hard-code one specific scheme, `download` takes the base
+//! URL to fetch `<edition>.dat.gz` from as a parameter; this module only
+//! owns the per-edition archive/local filenames and the gunzip-to-disk
+//! step.
+//!
+//! This pulls in an HTTP+TLS client (`ureq`) and a gzip decoder
+//! (`flate2`), which most consumers of the `GeoIp`/`mmdb` lookups never
+//! need, so the module is gated behind the `updater` Cargo feature rather
+//! than compiled unconditionally. Enabling it requires declaring both as
+//! optional dependencies:
+//!
+//! ```toml
+//! [dependencies]
+//! flate2 = { version = "1", optional = true }
+//! ureq = { version = "2", optional = true }
+//!
+//! [features]
+//! updater = ["dep:flate2", "dep:ureq"]
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use flate2::read::GzDecoder;
+
+use crate::DBType;
+
+/// The editions this module knows the legacy archive/local filenames for.
+/// Limited to the ones actually shipped as a `.dat.gz` archive by MaxMind's
+/// legacy update service, rather than guessing at names for editions (Org,
+/// ISP, Domain, NetSpeed, ...) that were never distributed that way.
+fn remote_and_local_names(db_type: &DBType) -> Option<(&'static str, &'static str)> {
+    match *db_type {
+        DBType::CountryEdition => Some(("GeoIP.dat.gz", "GeoIP.dat")),
+        DBType::CountryEditionV6 => Some(("GeoIPv6.dat.gz", "GeoIPv6.dat")),
+        DBType::CityEditionRev1 => Some(("GeoLiteCity.dat.gz", "GeoLiteCity.dat")),
+        DBType::CityEditionRev1V6 => Some(("GeoLiteCityv6.dat.gz", "GeoLiteCityv6.dat")),
+        DBType::ASNUMEdition => Some(("GeoIPASNum.dat.gz", "GeoIPASNum.dat")),
+        DBType::ASNumEditionV6 => Some(("GeoIPASNumv6.dat.gz", "GeoIPASNumv6.dat")),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum UpdateError {
+    UnsupportedEdition(DBType),
+    Download(String),
+    Decompress(String),
+    Io(String),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            UpdateError::UnsupportedEdition(ref t) => {
+                write!(f, "No known download for database edition {:?}", t)
+            }
+            UpdateError::Download(ref err) => write!(f, "Failed to download database: {}", err),
+            UpdateError::Decompress(ref err) => {
+                write!(f, "Failed to decompress downloaded database: {}", err)
+            }
+            UpdateError::Io(ref err) => write!(f, "Failed to write database file: {}", err),
+        }
+    }
+}
+
+impl Error for UpdateError {
+    fn description(&self) -> &str {
+        match *self {
+            UpdateError::UnsupportedEdition(_) => "no known download for this database edition",
+            UpdateError::Download(_) => "failed to download database",
+            UpdateError::Decompress(_) => "failed to decompress downloaded database",
+            UpdateError::Io(_) => "failed to write database file",
+        }
+    }
+}
+
+/// Where `db_type` would be written inside `dir`, if it is a known edition.
+pub fn local_path(db_type: &DBType, dir: &Path) -> Option<PathBuf> {
+    remote_and_local_names(db_type).map(|(_, local_name)| dir.join(local_name))
+}
+
+/// Download and gunzip `db_type` into `dir`, returning the path to the
+/// decompressed file, ready to pass to [`GeoIp::open`](crate::GeoIp::open).
+///
+/// `base_url` is joined with the edition's archive filename (e.g.
+/// `{base_url}/GeoIP.dat.gz`) to form the request URL, so it should already
+/// include any path prefix, and query string such as a license key, your
+/// MaxMind update endpoint requires. The filename goes after the path and
+/// ahead of any query string.
+pub fn download(db_type: &DBType, base_url: &str, dir: &Path) -> Result<PathBuf, UpdateError> {
+    let (remote_name, local_name) =
+        remote_and_local_names(db_type).ok_or_else(|| UpdateError::UnsupportedEdition(*db_type))?;
+
+    let (base, query) = match base_url.find('?') {
+        Some(at) => base_url.split_at(at),
+        None => (base_url, ""),
+    };
+    let url = format!("{}/{}{}", base.trim_end_matches('/'), remote_name, query);
+    let response = ureq::get(&url)
+        .call()
+        .map_err(|err| UpdateError::Download(err.to_string()))?;
+
+    fs::create_dir_all(dir).map_err(|err| UpdateError::Io(err.to_string()))?;
+    let dest_path = dir.join(local_name);
+    let mut dest = File::create(&dest_path).map_err(|err| UpdateError::Io(err.to_string()))?;
+
+    let mut decoder = GzDecoder::new(response.into_reader());
+    io::copy(&mut decoder, &mut dest).map_err(|err| UpdateError::Decompress(err.to_string()))?;
+    dest.flush().map_err(|err| UpdateError::Io(err.to_string()))?;
+
+    Ok(dest_path)
+}
+
+/// Whether the file at `path` is older than `max_age`, or missing entirely.
+pub fn is_stale(path: &Path, max_age: Duration) -> bool {
+    let modified = match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return true,
+    };
+    SystemTime::now()
+        .duration_since(modified)
+        .map(|age| age > max_age)
+        .unwrap_or(false)
+}
+
+/// Whether `db_type`'s local copy in `dir` should be refreshed: either it
+/// hasn't been downloaded yet, or it is older than `max_age`.
+pub fn needs_update(db_type: &DBType, dir: &Path, max_age: Duration) -> bool {
+    match local_path(db_type, dir) {
+        Some(path) => is_stale(&path, max_age),
+        None => false,
+    }
+}